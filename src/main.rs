@@ -8,21 +8,93 @@ use std::{
 pub mod plainsong;
 
 fn help() {
-    println!("Usage: plainsong <to-ron|to-latex> [filename]");
+    println!(
+        "Usage: plainsong <to-ron|to-json|to-latex|to-chordpro> [--transpose N] [--flats] \
+         [--notation english|german|nashville] [--key KEY] [--plan NAME] \
+         [--format plain|chordpro] [--from ron|json] [filename]"
+    );
+}
+
+fn parse_notation(value: &str) -> plainsong::Notation {
+    match value {
+        "english" => plainsong::Notation::English,
+        "german" => plainsong::Notation::German,
+        "nashville" => plainsong::Notation::Nashville,
+        _ => panic!(
+            "Unknown notation '{}': expected english, german or nashville",
+            value
+        ),
+    }
+}
+
+fn parse_format(value: &str) -> plainsong::InputFormat {
+    match value {
+        "plain" => plainsong::InputFormat::Plain,
+        "chordpro" => plainsong::InputFormat::ChordPro,
+        _ => panic!("Unknown format '{}': expected plain or chordpro", value),
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
+    let mut positional = Vec::new();
+    let mut transpose: Option<i32> = None;
+    let mut prefer_flats = false;
+    let mut notation = plainsong::Notation::default();
+    let mut key: Option<String> = None;
+    let mut plan: Option<String> = None;
+    let mut format = plainsong::InputFormat::default();
+    let mut from: Option<String> = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_ref() {
+            "--transpose" => {
+                i += 1;
+                let value = args.get(i).expect("--transpose requires a value");
+                transpose = Some(value.parse().expect("--transpose value must be an integer"));
+            }
+            "--flats" => {
+                prefer_flats = true;
+            }
+            "--notation" => {
+                i += 1;
+                let value = args.get(i).expect("--notation requires a value");
+                notation = parse_notation(value);
+            }
+            "--key" => {
+                i += 1;
+                key = Some(args.get(i).expect("--key requires a value").clone());
+            }
+            "--plan" => {
+                i += 1;
+                plan = Some(args.get(i).expect("--plan requires a value").clone());
+            }
+            "--format" => {
+                i += 1;
+                let value = args.get(i).expect("--format requires a value");
+                format = parse_format(value);
+            }
+            "--from" => {
+                i += 1;
+                from = Some(args.get(i).expect("--from requires a value").clone());
+            }
+            other => positional.push(other.to_string()),
+        }
+        i += 1;
+    }
+
     let mut content = String::new();
-    match args.len() {
-        2 => {
+    match positional.len() {
+        1 => {
             eprintln!("Filename has been omitted, reading from stdin");
             io::stdin().read_to_string(&mut content).unwrap();
         }
-        3 => {
-            eprintln!("Reading plain song from {}", args[2]);
-            content = fs::read_to_string(&args[2]).expect("Something went wrong reading the file");
+        2 => {
+            eprintln!("Reading song from {}", positional[1]);
+            content = fs::read_to_string(&positional[1])
+                .expect("Something went wrong reading the file");
         }
         _ => {
             help();
@@ -30,15 +102,49 @@ fn main() {
         }
     }
 
-    let mut song = plainsong::SongParser::parse(&content);
+    let mut song: plainsong::Song = match from.as_deref() {
+        Some("ron") => ron::de::from_str(&content).expect("Failed to parse RON input"),
+        Some("json") => serde_json::from_str(&content).expect("Failed to parse JSON input"),
+        Some(other) => panic!("Unknown input format '{}': expected ron or json", other),
+        None => plainsong::SongParser::parse(&content, format)
+            .unwrap_or_else(|err| panic!("Failed to parse plain song: {}", err)),
+    };
+
+    if let Some(semitones) = transpose {
+        song.transpose(semitones, prefer_flats);
+    }
+    if let Some(key) = key {
+        song.set_key(key);
+    }
 
-    match args[1].as_ref() {
+    match positional[0].as_ref() {
         "to-ron" => {
-            println!("{:#?}", song);
+            println!(
+                "{}",
+                ron::ser::to_string_pretty(&song, ron::ser::PrettyConfig::default())
+                    .expect("Failed to serialize song as RON")
+            );
         }
-        "to-latex" => {
-            println!("{}", &song.to_latex());
+        "to-json" => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&song).expect("Failed to serialize song as JSON")
+            );
         }
+        "to-latex" => match song.to_latex(notation, plan.as_deref()) {
+            Ok(out) => println!("{}", out),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        },
+        "to-chordpro" => match song.to_chordpro(plan.as_deref()) {
+            Ok(out) => println!("{}", out),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        },
         _ => {
             help();
             return;
@@ -1,15 +1,209 @@
 use regex::Regex;
-use std::{collections::HashMap, mem};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+};
 
 const CHORD_REGEX: &str = "^(C|D|E|F|G|A|B)(b|#)?(m|M|min|maj|dim|Δ|°|ø|Ø)?((sus|add)?(b|#)?\
                             (2|4|5|6|7|9|10|11|13)?)*(\\+|aug|alt)?(/(C|D|E|F|G|A|B)(b|#)?)?$";
 
-#[derive(Default, Debug, Eq, PartialEq)]
+// Splits a chord name into root, accidental, the untouched quality/extension
+// suffix and an optional bass note, so the suffix can be carried through a
+// transposition unchanged.
+const CHORD_PARTS_REGEX: &str = "^(C|D|E|F|G|A|B)(b|#)?(.*?)(?:/(C|D|E|F|G|A|B)(b|#)?)?$";
+
+const SHARP_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+const FLAT_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+
+fn pitch_class(root: &str, accidental: Option<&str>) -> i32 {
+    let base = match root {
+        "C" => 0,
+        "D" => 2,
+        "E" => 4,
+        "F" => 5,
+        "G" => 7,
+        "A" => 9,
+        "B" => 11,
+        _ => unreachable!("root is constrained by CHORD_PARTS_REGEX"),
+    };
+
+    match accidental {
+        Some("#") => base + 1,
+        Some("b") => base - 1,
+        _ => base,
+    }
+}
+
+fn spell_pitch_class(pc: i32, prefer_flats: bool) -> &'static str {
+    let pc = pc.rem_euclid(12) as usize;
+    if prefer_flats {
+        FLAT_NAMES[pc]
+    } else {
+        SHARP_NAMES[pc]
+    }
+}
+
+/// Degree names of the Nashville Number System for each semitone above the
+/// key's tonic, e.g. a chord a major third above the key is scale degree 3,
+/// a minor third is `b3`.
+const NASHVILLE_DEGREES: [&str; 12] = [
+    "1", "b2", "2", "b3", "3", "4", "#4", "5", "b6", "6", "b7", "7",
+];
+
+/// The chord notation a song is rendered in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Notation {
+    English,
+    German,
+    Nashville,
+}
+
+impl Default for Notation {
+    fn default() -> Notation {
+        Notation::English
+    }
+}
+
+#[derive(Debug)]
+pub enum NotationError {
+    /// Nashville notation was requested but the song has no resolvable key.
+    MissingKey,
+}
+
+impl std::fmt::Display for NotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NotationError::MissingKey => write!(
+                f,
+                "Nashville notation requires a key (add a `key:` metadata line or pass --key)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+fn german_spelling(root: &str, accidental: Option<&str>) -> String {
+    match (root, accidental) {
+        ("B", None) => String::from("H"),
+        ("B", Some("b")) => String::from("B"),
+        (root, Some(accidental)) => format!("{}{}", root, accidental),
+        (root, None) => String::from(root),
+    }
+}
+
+fn nashville_degree(root: &str, accidental: Option<&str>, key: &str) -> String {
+    lazy_static! {
+        static ref KEY_RE: Regex = Regex::new(r"^\s*(C|D|E|F|G|A|B)(b|#)?").unwrap();
+    };
+
+    let key_pc = match KEY_RE.captures(key) {
+        Some(groups) => pitch_class(&groups[1], groups.get(2).map(|m| m.as_str())),
+        None => 0,
+    };
+
+    let interval = pitch_class(root, accidental) - key_pc;
+    NASHVILLE_DEGREES[interval.rem_euclid(12) as usize].to_string()
+}
+
+/// Renders a chord name in the requested notation. `key` is the song's key
+/// and is only consulted (and required) for `Notation::Nashville`.
+fn render_chord_name(name: &str, notation: Notation, key: Option<&str>) -> String {
+    if let Notation::English = notation {
+        return name.to_string();
+    }
+
+    lazy_static! {
+        static ref RE: Regex = Regex::new(CHORD_PARTS_REGEX).unwrap();
+    };
+
+    let groups = match RE.captures(name) {
+        Some(groups) => groups,
+        // Not a chord we recognize the structure of; leave it as is.
+        None => return name.to_string(),
+    };
+
+    let root = match notation {
+        Notation::German => german_spelling(&groups[1], groups.get(2).map(|m| m.as_str())),
+        Notation::Nashville => {
+            nashville_degree(&groups[1], groups.get(2).map(|m| m.as_str()), key.unwrap())
+        }
+        Notation::English => unreachable!(),
+    };
+    let suffix = &groups[3];
+
+    match (groups.get(4), groups.get(5)) {
+        (Some(bass_root), bass_acc) => {
+            let bass = match notation {
+                Notation::German => {
+                    german_spelling(bass_root.as_str(), bass_acc.map(|m| m.as_str()))
+                }
+                Notation::Nashville => nashville_degree(
+                    bass_root.as_str(),
+                    bass_acc.map(|m| m.as_str()),
+                    key.unwrap(),
+                ),
+                Notation::English => unreachable!(),
+            };
+            format!("{}{}/{}", root, suffix, bass)
+        }
+        (None, _) => format!("{}{}", root, suffix),
+    }
+}
+
+/// A `#`-introduced annotation lifted out of the plaintext source (capo
+/// notes, performance cues, ...). Kept alongside the line/part/song it was
+/// found on so a RON/JSON round-trip doesn't lose it, even though rendering
+/// it back out is future work (a `to_plainsong` emitter).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SongComment {
+    /// 1-indexed source line the comment was found on.
+    line: u32,
+    text: String,
+}
+
+#[derive(Default, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct SongChord {
     name: String,
     pos: u32,
 }
 
+impl SongChord {
+    fn transpose(&mut self, semitones: i32, prefer_flats: bool) {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(CHORD_PARTS_REGEX).unwrap();
+        };
+
+        let groups = match RE.captures(&self.name) {
+            Some(groups) => groups,
+            // Not a chord we recognize the structure of; leave it as is.
+            None => return,
+        };
+
+        let root = spell_pitch_class(
+            pitch_class(&groups[1], groups.get(2).map(|m| m.as_str())) + semitones,
+            prefer_flats,
+        );
+        let suffix = &groups[3];
+
+        self.name = match (groups.get(4), groups.get(5)) {
+            (Some(bass_root), bass_acc) => {
+                let bass = spell_pitch_class(
+                    pitch_class(bass_root.as_str(), bass_acc.map(|m| m.as_str())) + semitones,
+                    prefer_flats,
+                );
+                format!("{}{}/{}", root, suffix, bass)
+            }
+            (None, _) => format!("{}{}", root, suffix),
+        };
+    }
+}
+
 impl Ord for SongChord {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.pos.cmp(&other.pos)
@@ -22,14 +216,22 @@ impl PartialOrd for SongChord {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct SongLine {
     text: String,
     chords: Vec<SongChord>,
+    /// A trailing `# ...` comment captured from this line's source, if any.
+    comments: Option<Vec<SongComment>>,
 }
 
 impl SongLine {
-    pub fn to_latex(&mut self) -> String {
+    fn transpose(&mut self, semitones: i32, prefer_flats: bool) {
+        for chord in self.chords.iter_mut() {
+            chord.transpose(semitones, prefer_flats);
+        }
+    }
+
+    pub fn to_latex(&mut self, notation: Notation, key: Option<&str>) -> String {
         if self.chords.is_empty() {
             return format!("{}\n", self.text);
         }
@@ -45,7 +247,8 @@ impl SongLine {
         }
 
         for chord in self.chords.iter() {
-            out.insert_str(chord.pos as usize, &format!("\\[{}]", chord.name));
+            let name = render_chord_name(&chord.name, notation, key);
+            out.insert_str(chord.pos as usize, &format!("\\[{}]", name));
         }
 
         if self.text.is_empty() {
@@ -55,16 +258,59 @@ impl SongLine {
         format!("{}\n", out)
     }
 
-    fn to_html(&self) -> String {
+    /// Renders this line back into ChordPro's inline-bracket style, e.g.
+    /// `A[D]mazing [G]grace`. A chord-only line (no lyric text) is rendered
+    /// as a standalone `[C] [G]` line instead of padding an empty line out
+    /// to the chords' original positions.
+    pub fn to_chordpro(&mut self) -> String {
+        if self.chords.is_empty() {
+            return format!("{}\n", self.text);
+        }
+
+        if self.text.is_empty() {
+            self.chords.sort_unstable();
+            let names: Vec<String> = self
+                .chords
+                .iter()
+                .map(|chord| format!("[{}]", chord.name))
+                .collect();
+            return format!("{}\n", names.join(" "));
+        }
+
+        let mut out = self.text.clone();
+
+        self.chords.sort_unstable();
+        self.chords.reverse();
+
+        let diff = self.chords[0].pos as i32 - out.len() as i32;
+        if diff > 0 {
+            out.push_str(&" ".repeat(diff as usize));
+        }
+
+        for chord in self.chords.iter() {
+            out.insert_str(chord.pos as usize, &format!("[{}]", chord.name));
+        }
+
+        format!("{}\n", out)
+    }
+
+    fn to_html(&self, notation: Notation, key: Option<&str>) -> String {
         let mut out = String::new();
 
         if !self.chords.is_empty() {
             out.push_str("<b>");
-            let mut last = 0;
+            // Tracks where the previous chord's *rendered* name actually
+            // ended, rather than assuming it's as wide as the original
+            // (English) spelling. A chord that widens under Nashville/
+            // German notation (e.g. a slash chord needing two accidentals)
+            // would otherwise make the next chord's gap go negative.
+            let mut rendered_end: i32 = 0;
             for chord in self.chords.iter() {
-                out.push_str(&" ".repeat((chord.pos - last) as usize));
-                out.push_str(&chord.name);
-                last = chord.pos + chord.name.len() as u32;
+                let name = render_chord_name(&chord.name, notation, key);
+                let gap = (chord.pos as i32 - rendered_end).max(0);
+                out.push_str(&" ".repeat(gap as usize));
+                out.push_str(&name);
+                rendered_end += gap + name.len() as i32;
             }
             out.push_str("</b>\n");
         }
@@ -78,10 +324,14 @@ impl SongLine {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct SongPart {
     name: String,
     lines: Vec<SongLine>,
+    /// Comment lines captured from this part's source that stand alone
+    /// (not attached to a particular lyric/chord line), e.g. a `#` line
+    /// sitting between the section header and its first lyric.
+    comments: Option<Vec<SongComment>>,
 }
 
 impl SongPart {
@@ -89,7 +339,13 @@ impl SongPart {
         self.name.is_empty() && self.lines.is_empty()
     }
 
-    pub fn to_latex(&mut self) -> String {
+    fn transpose(&mut self, semitones: i32, prefer_flats: bool) {
+        for line in self.lines.iter_mut() {
+            line.transpose(semitones, prefer_flats);
+        }
+    }
+
+    pub fn to_latex(&mut self, notation: Notation, key: Option<&str>) -> String {
         let mut out = String::new();
 
         lazy_static! {
@@ -116,36 +372,220 @@ impl SongPart {
 
         for line in self.lines.iter_mut() {
             out.push_str("\t");
-            out.push_str(&line.to_latex());
+            out.push_str(&line.to_latex(notation, key));
         }
 
         out.push_str(end);
         out
     }
 
-    fn to_html(&self) -> String {
+    fn to_html(&self, notation: Notation, key: Option<&str>) -> String {
         let mut out = String::new();
 
         // Add title
         out.push_str(&format!("<em>{}:</em>\n", self.name));
 
         for line in self.lines.iter() {
-            out.push_str(&line.to_html());
+            out.push_str(&line.to_html(notation, key));
+        }
+
+        out
+    }
+
+    fn to_chordpro(&mut self) -> String {
+        let mut out = String::new();
+
+        if !self.name.is_empty() {
+            out.push_str(&format!("{}:\n", self.name));
+        }
+
+        for line in self.lines.iter_mut() {
+            out.push_str(&line.to_chordpro());
         }
 
         out
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
+pub enum PlanError {
+    /// A plan was requested by name but no such plan was defined.
+    UnknownPlan(String),
+    /// A plan (named or the default) references a section that was never defined.
+    UnknownSection(String),
+}
+
+impl std::fmt::Display for PlanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PlanError::UnknownPlan(name) => write!(f, "no plan named '{}' is defined", name),
+            PlanError::UnknownSection(name) => {
+                write!(f, "plan references unknown section '{}'", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+#[derive(Debug)]
+pub enum RenderError {
+    Notation(NotationError),
+    Plan(PlanError),
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RenderError::Notation(err) => err.fmt(f),
+            RenderError::Plan(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<NotationError> for RenderError {
+    fn from(err: NotationError) -> RenderError {
+        RenderError::Notation(err)
+    }
+}
+
+impl From<PlanError> for RenderError {
+    fn from(err: PlanError) -> RenderError {
+        RenderError::Plan(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum SongParseError {
+    /// Two sections in the source shared the same name; keeping both would
+    /// mean one silently clobbers the other in `Song::parts`.
+    DuplicateSection(String),
+    /// A `plan:`/`arrangement:` line defined in the source references a
+    /// section that doesn't exist, caught up front instead of only when a
+    /// caller happens to ask for that plan by name.
+    Plan(PlanError),
+}
+
+impl std::fmt::Display for SongParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SongParseError::DuplicateSection(name) => {
+                write!(f, "section '{}' is defined more than once", name)
+            }
+            SongParseError::Plan(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SongParseError {}
+
+impl From<PlanError> for SongParseError {
+    fn from(err: PlanError) -> SongParseError {
+        SongParseError::Plan(err)
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Song {
     title: String,
     metadata: HashMap<String, String>,
-    parts: Vec<SongPart>,
+    parts: HashMap<String, SongPart>,
+    /// Section keys in the order they were first defined in the source,
+    /// used as the default plan when none is given.
+    order: Vec<String>,
+    /// Named arrangements of section keys, keyed by plan name; the default
+    /// plan (from a bare `plan:`/`arrangement:` line) is stored under `""`.
+    plans: HashMap<String, Vec<String>>,
+    /// Comment lines captured before any section started (title/metadata
+    /// phase), or standalone comment lines found between sections.
+    comments: Option<Vec<SongComment>>,
 }
 
 impl Song {
-    pub fn to_latex(&mut self) -> String {
+    /// Stores a freshly parsed section, keying it by its name so a `plan`
+    /// can reference it later. Sections without a name (no colon-terminated
+    /// header line) get a synthetic key and can only be reached through the
+    /// default, document-order plan. Returns the section's name if it was
+    /// already in use, so the caller can turn that into a parse error
+    /// instead of silently losing the earlier section's content.
+    fn add_part(&mut self, part: SongPart) -> Option<String> {
+        let key = if part.name.is_empty() {
+            format!("#{}", self.order.len())
+        } else {
+            part.name.clone()
+        };
+
+        let duplicate = (!part.name.is_empty() && self.parts.contains_key(&key))
+            .then(|| key.clone());
+
+        self.order.push(key.clone());
+        self.parts.insert(key, part);
+        duplicate
+    }
+
+    /// Checks every plan defined in the source (the default plan and every
+    /// named one) resolves cleanly, so a broken named plan is caught at
+    /// parse time even if no caller ever asks to render it.
+    fn validate_plans(&self) -> Result<(), PlanError> {
+        for name in self.plans.keys() {
+            let name = if name.is_empty() { None } else { Some(name.as_str()) };
+            self.plan(name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Resolves a plan by name (or the default plan, falling back to
+    /// document order) into the ordered list of section keys it references.
+    pub fn plan(&self, name: Option<&str>) -> Result<Vec<String>, PlanError> {
+        let names = match name {
+            Some(name) => self
+                .plans
+                .get(name)
+                .ok_or_else(|| PlanError::UnknownPlan(name.to_string()))?,
+            None => self.plans.get("").unwrap_or(&self.order),
+        };
+
+        for name in names {
+            if !self.parts.contains_key(name) {
+                return Err(PlanError::UnknownSection(name.clone()));
+            }
+        }
+
+        Ok(names.clone())
+    }
+
+    /// Shifts every chord in the song up or down by `semitones`, respelling
+    /// black-key roots as flats when `prefer_flats` is set and as sharps
+    /// otherwise. Only the root and bass letters change; the quality/
+    /// extension suffix (e.g. `sus4`, `maj7`, `ø`) passes through verbatim,
+    /// so a widened or narrowed root name may shift `to_latex`/`to_html`
+    /// alignment by a column.
+    pub fn transpose(&mut self, semitones: i32, prefer_flats: bool) {
+        for part in self.parts.values_mut() {
+            part.transpose(semitones, prefer_flats);
+        }
+    }
+
+    /// Overrides (or sets) the song's key, e.g. to honor a `--key` CLI flag
+    /// that should take precedence over a parsed `key:` metadata line.
+    pub fn set_key(&mut self, key: String) {
+        self.metadata.insert(String::from("key"), key);
+    }
+
+    pub fn to_latex(
+        &mut self,
+        notation: Notation,
+        plan: Option<&str>,
+    ) -> Result<String, RenderError> {
+        let key = self.metadata.get("key").map(|key| key.as_str());
+        if let (Notation::Nashville, None) = (notation, key) {
+            return Err(NotationError::MissingKey.into());
+        }
+        let resolved = self.plan(plan)?;
+
         let mut out = String::new();
 
         // Begin the song
@@ -157,19 +597,38 @@ impl Song {
         }
         out.push_str("\n\n");
 
-        // Insert parts
-        for part in self.parts.iter_mut() {
-            out.push_str(&part.to_latex());
+        // Insert parts, collapsing a repeated chorus into the songs
+        // package's `\chorus` repeat shorthand instead of emitting it again.
+        let mut seen_choruses = HashSet::new();
+        for section in resolved.iter() {
+            let is_chorus = self.parts[section].name.to_lowercase() == "chorus";
+            if is_chorus && !seen_choruses.insert(section.clone()) {
+                out.push_str("\\chorus\n\n");
+                continue;
+            }
+
+            let part = self.parts.get_mut(section).expect("checked by plan()");
+            out.push_str(&part.to_latex(notation, key));
             out.push_str("\n");
         }
 
         // End the song
         out.push_str("\\endsong\n");
 
-        out
+        Ok(out)
     }
 
-    pub fn to_html(&mut self) -> String {
+    pub fn to_html(
+        &mut self,
+        notation: Notation,
+        plan: Option<&str>,
+    ) -> Result<String, RenderError> {
+        let key = self.metadata.get("key").map(|key| key.as_str());
+        if let (Notation::Nashville, None) = (notation, key) {
+            return Err(NotationError::MissingKey.into());
+        }
+        let resolved = self.plan(plan)?;
+
         let mut out = String::new();
 
         // Surround with pre tag
@@ -185,18 +644,63 @@ impl Song {
         out.push_str("\n\n");
 
         // Add parts
-        for part in self.parts.iter() {
-            out.push_str(&part.to_html());
+        for section in resolved.iter() {
+            out.push_str(&self.parts[section].to_html(notation, key));
             out.push_str("\n\n");
         }
 
         // Close pre tag
         out.push_str("</pre>");
 
-        out
+        Ok(out)
+    }
+
+    /// Renders the song back into the ChordPro inline-bracket format,
+    /// the inverse of `SongParser::parse(content, InputFormat::ChordPro)`.
+    /// Walks `plan` like `to_latex`/`to_html` do, so a chorus repeated via
+    /// the plan mechanism (or a custom section order) comes through intact
+    /// instead of falling back to document order. Plan lines themselves are
+    /// not written out, and a section the plan repeats is emitted once per
+    /// repeat, so such output parses back as a duplicate-section error.
+    pub fn to_chordpro(&mut self, plan: Option<&str>) -> Result<String, RenderError> {
+        let resolved = self.plan(plan)?;
+
+        let mut out = String::new();
+
+        out.push_str(&format!("{}\n\n", self.title));
+
+        for (k, v) in self.metadata.iter() {
+            out.push_str(&format!("{}: {}\n", k, v));
+        }
+        if !self.metadata.is_empty() {
+            out.push_str("\n");
+        }
+
+        for key in resolved.iter() {
+            let part = self.parts.get_mut(key).expect("checked by plan()");
+            out.push_str(&part.to_chordpro());
+            out.push_str("\n");
+        }
+
+        Ok(out)
     }
 }
 
+/// Splits a source line into its content and an optional trailing `#`
+/// comment. A `#` only starts a comment at the very start of the line or
+/// when preceded by whitespace, so it can't swallow a chord's sharp
+/// accidental (e.g. the `#` in `G#`).
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let bytes = line.as_bytes();
+    for (i, c) in line.char_indices() {
+        if c == '#' && (i == 0 || bytes[i - 1] == b' ' || bytes[i - 1] == b'\t') {
+            return (&line[..i], Some(line[i + 1..].trim()));
+        }
+    }
+
+    (line, None)
+}
+
 enum SongParserState {
     START,
     DEFINITION,
@@ -209,28 +713,131 @@ impl Default for SongParserState {
     }
 }
 
+/// The lyric-line syntax a song is parsed from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputFormat {
+    /// Chords sit on their own line above the lyric line they align with.
+    Plain,
+    /// Chords are inlined in the lyric as `[C]` at the position they ring.
+    ChordPro,
+}
+
+impl Default for InputFormat {
+    fn default() -> InputFormat {
+        InputFormat::Plain
+    }
+}
+
 #[derive(Default)]
 pub struct SongParser {
     song: Song,
     state: SongParserState,
     last_chords: Vec<SongChord>,
     part: SongPart,
+    format: InputFormat,
+    /// Source line number (1-indexed) of the line currently being parsed.
+    line_no: u32,
+    /// A trailing `# ...` comment split off the line currently being
+    /// parsed, held here until the dispatch below knows what it belongs to.
+    pending_comment: Option<String>,
+    /// Set the first time a section name collides with one already parsed;
+    /// kept here (rather than threaded through `parse_line`'s many call
+    /// sites) and checked once parsing finishes.
+    duplicate_section: Option<String>,
 }
 
 impl SongParser {
-    pub fn parse(content: &str) -> Song {
-        let mut parser = SongParser::default();
+    pub fn parse(content: &str, format: InputFormat) -> Result<Song, SongParseError> {
+        let mut parser = SongParser {
+            format,
+            ..SongParser::default()
+        };
 
         for line in content.lines() {
+            parser.line_no += 1;
             parser.parse_line(line);
         }
+        parser.line_no += 1;
         parser.parse_line("");
 
-        parser.song
+        if let Some(name) = parser.duplicate_section {
+            return Err(SongParseError::DuplicateSection(name));
+        }
+
+        parser.song.validate_plans()?;
+
+        Ok(parser.song)
+    }
+
+    /// Takes the comment, if any, pending from the line currently being
+    /// parsed, wrapped ready to attach to a `SongLine`.
+    fn take_pending_comment(&mut self) -> Option<Vec<SongComment>> {
+        self.pending_comment.take().map(|text| {
+            vec![SongComment {
+                line: self.line_no,
+                text,
+            }]
+        })
+    }
+
+    fn flush_pending_comment_to_song(&mut self) {
+        if let Some(text) = self.pending_comment.take() {
+            self.song.comments.get_or_insert_with(Vec::new).push(SongComment {
+                line: self.line_no,
+                text,
+            });
+        }
+    }
+
+    fn flush_pending_comment_to_part(&mut self) {
+        if let Some(text) = self.pending_comment.take() {
+            self.part.comments.get_or_insert_with(Vec::new).push(SongComment {
+                line: self.line_no,
+                text,
+            });
+        }
+    }
+
+    /// Records a standalone comment line (nothing else on the line) against
+    /// whichever part of the song is currently being parsed. A comment line
+    /// outside any section (before the first one or between two) goes on
+    /// the song itself.
+    fn record_comment(&mut self, text: String) {
+        let comment = SongComment {
+            line: self.line_no,
+            text,
+        };
+
+        match self.state {
+            SongParserState::BODY if !self.part.is_empty() => {
+                self.part.comments.get_or_insert_with(Vec::new).push(comment)
+            }
+            _ => self.song.comments.get_or_insert_with(Vec::new).push(comment),
+        }
     }
 
     fn parse_line(&mut self, line: &str) {
-        let trimmed_line = line.trim();
+        // Split off a `#` comment before anything else sees the line, so it
+        // can't be mistaken for a chord line (`#` is also a sharp
+        // accidental) or leak into lyrics/metadata.
+        let (content, comment) = split_comment(line);
+        let trimmed_line = content.trim();
+
+        if let Some(comment) = comment {
+            if trimmed_line.is_empty() {
+                // The whole line is a comment; record it and stop, so it
+                // isn't also treated as the blank line that ends a part.
+                self.record_comment(comment.to_string());
+                return;
+            }
+
+            // A trailing comment rides along with whatever this line turns
+            // out to be (title, metadata, chord line, or lyric); attached
+            // once that's known, below.
+            self.pending_comment = Some(comment.to_string());
+        }
+
+        let line = content;
 
         match self.state {
             SongParserState::START => {
@@ -242,6 +849,7 @@ impl SongParser {
                 // The first line with content is the song title
                 self.song.title = String::from(trimmed_line);
                 self.state = SongParserState::DEFINITION;
+                self.flush_pending_comment_to_song();
             }
             SongParserState::DEFINITION => {
                 // Ignore blank lines
@@ -250,13 +858,19 @@ impl SongParser {
                 }
 
                 // Parse either metadata or the first song part
-                if !self.parse_metadata(line) {
+                if self.parse_metadata(line) {
+                    self.flush_pending_comment_to_song();
+                } else {
                     self.state = SongParserState::BODY;
                     self.parse_line(line);
                 }
             }
             SongParserState::BODY => {
-                // If there is a blank line, push any non empty part and reset it
+                // A blank line is a pure separator between parts, regardless
+                // of input format; it must never reach `parse_part` below,
+                // since for ChordPro that would push an empty `SongLine`
+                // into the next part and make it look non-empty, causing
+                // that part's own `"Name:"` header to be missed.
                 if trimmed_line.is_empty() {
                     if self.part.is_empty() {
                         return;
@@ -266,10 +880,15 @@ impl SongParser {
                         self.part.lines.push(SongLine {
                             text: String::new(),
                             chords: self.last_chords.drain(..).collect(),
+                            comments: None,
                         });
                     }
 
-                    self.song.parts.push(mem::take(&mut self.part));
+                    if let Some(name) = self.song.add_part(mem::take(&mut self.part)) {
+                        self.duplicate_section.get_or_insert(name);
+                    }
+
+                    return;
                 }
 
                 // Otherwise parse the line to the current part
@@ -280,15 +899,32 @@ impl SongParser {
 
     fn parse_metadata(&mut self, line: &str) -> bool {
         lazy_static! {
+            // `plan:`/`arrangement:` (optionally named, e.g. `plan acoustic:`)
+            // lists section names in order instead of storing a plain value.
+            static ref PLAN_RE: Regex =
+                Regex::new(r"^\s*(?:plan|arrangement)(?:\s+([^:]+?))?\s*:\s*(.*)\s*$").unwrap();
             static ref RE: Regex = Regex::new(r"^\s*(.*): (.*)\s*$").unwrap();
         };
 
+        if let Some(groups) = PLAN_RE.captures(line) {
+            let plan_name = groups
+                .get(1)
+                .map_or(String::new(), |m| m.as_str().trim().to_string());
+            let sections = groups[2].split(',').map(|s| s.trim().to_string()).collect();
+
+            self.song.plans.insert(plan_name, sections);
+            return true;
+        }
+
         match RE.captures(line) {
             None => false,
             Some(groups) => {
+                // The value group is greedy and happily absorbs the
+                // whitespace `split_comment` left behind after stripping a
+                // trailing `#` comment, so trim it before storing.
                 self.song
                     .metadata
-                    .insert(String::from(&groups[1]), String::from(&groups[2]));
+                    .insert(String::from(&groups[1]), String::from(groups[2].trim()));
                 true
             }
         }
@@ -304,10 +940,18 @@ impl SongParser {
 
             if let Some(groups) = RE.captures(line) {
                 self.part.name = String::from(&groups[1]);
+                self.flush_pending_comment_to_part();
                 return;
             }
         }
 
+        if let InputFormat::ChordPro = self.format {
+            let mut line = self.parse_chordpro_line(line);
+            line.comments = self.take_pending_comment();
+            self.part.lines.push(line);
+            return;
+        }
+
         // Try to interpret as a chord line
         if let Some(mut chords) = self.parse_chords(line) {
             // If the last line had chords, then put them on their own line
@@ -316,19 +960,75 @@ impl SongParser {
                 self.part.lines.push(SongLine {
                     text: String::new(),
                     chords,
+                    comments: None,
                 })
             }
 
+            // This line's chords ride along on `last_chords` until the lyric
+            // line that follows; a trailing comment can't travel with them,
+            // so it's kept on the part instead of being dropped.
+            self.flush_pending_comment_to_part();
+
             return;
         }
 
         // If the line is not a chord line, then save it with its chords
+        let comments = self.take_pending_comment();
         self.part.lines.push(SongLine {
             text: String::from(line),
             chords: mem::take(&mut self.last_chords),
+            comments,
         })
     }
 
+    /// Parses a ChordPro-style lyric line, stripping `[...]` spans into
+    /// `SongChord`s recorded at the (stripped) text position the bracket
+    /// opened at. A bracket whose contents aren't a recognized chord is
+    /// left in the text verbatim.
+    fn parse_chordpro_line(&mut self, line: &str) -> SongLine {
+        let mut text = String::new();
+        let mut chords = Vec::new();
+        let mut pos: u32 = 0;
+
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c != '[' {
+                text.push(c);
+                pos += 1;
+                continue;
+            }
+
+            let mut contents = String::new();
+            let mut closed = false;
+            for next in chars.by_ref() {
+                if next == ']' {
+                    closed = true;
+                    break;
+                }
+                contents.push(next);
+            }
+
+            if closed && SongParser::is_chord(&contents) {
+                chords.push(SongChord { name: contents, pos });
+                continue;
+            }
+
+            text.push('[');
+            text.push_str(&contents);
+            pos += 1 + contents.chars().count() as u32;
+            if closed {
+                text.push(']');
+                pos += 1;
+            }
+        }
+
+        SongLine {
+            text,
+            chords,
+            comments: None,
+        }
+    }
+
     fn parse_chords(&mut self, line: &str) -> Option<Vec<SongChord>> {
         let mut word = String::new();
         let mut chords = Vec::new();
@@ -379,3 +1079,158 @@ impl SongParser {
         RE.is_match(&text)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Song {
+        SongParser::parse(content, InputFormat::Plain).expect("should parse")
+    }
+
+    #[test]
+    fn duplicate_section_name_is_a_parse_error() {
+        let content = "Title\n\nVerse 1:\nFirst verse lyrics\n\nVerse 1:\nSecond verse lyrics\n";
+
+        match SongParser::parse(content, InputFormat::Plain) {
+            Err(SongParseError::DuplicateSection(name)) => assert_eq!(name, "Verse 1"),
+            other => panic!("expected a duplicate-section parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_section_in_a_named_plan_is_a_parse_error() {
+        let content = "Title\n\
+                        plan acoustic: Verse 1, Bogus Section\n\
+                        \n\
+                        Verse 1:\n\
+                        Lyrics\n";
+
+        match SongParser::parse(content, InputFormat::Plain) {
+            Err(SongParseError::Plan(PlanError::UnknownSection(name))) => {
+                assert_eq!(name, "Bogus Section")
+            }
+            other => panic!("expected an unknown-section plan error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn trailing_comment_does_not_leave_whitespace_in_metadata_value() {
+        let content = "Title\nartist: Some Band  # as recorded live\n\nVerse 1:\nLyrics\n";
+        let mut song = parse(content);
+
+        let out = song.to_latex(Notation::English, None).expect("should render");
+
+        assert!(out.contains("[by={Some Band}"));
+        assert!(!out.contains("Some Band  "));
+    }
+
+    #[test]
+    fn to_chordpro_honors_the_songs_plan() {
+        let content = "Title\nplan: Verse 2, Verse 1\n\nVerse 1:\nFirst verse\n\nVerse 2:\nSecond verse\n";
+        let mut song = parse(content);
+
+        let out = song.to_chordpro(None).expect("should render");
+
+        let verse_2_pos = out.find("Verse 2:").expect("Verse 2 present");
+        let verse_1_pos = out.find("Verse 1:").expect("Verse 1 present");
+        assert!(verse_2_pos < verse_1_pos);
+    }
+
+    #[test]
+    fn chordpro_input_keeps_later_sections_separate() {
+        let content = "Title\n\n\
+                        Verse 1:\n\
+                        [C]First verse line\n\
+                        \n\
+                        Verse 2:\n\
+                        [G]Second verse line\n";
+
+        let mut song = SongParser::parse(content, InputFormat::ChordPro).expect("should parse");
+
+        let out = song.to_latex(Notation::English, None).expect("should render");
+        assert!(out.contains("First verse line"));
+        assert!(out.contains("Second verse line"));
+        // "Verse 2:" must not have been swallowed as lyric text inside
+        // Verse 1's part, and no spurious unnamed section should appear.
+        assert!(!out.contains("Verse 2:\n"));
+        assert_eq!(out.matches("\\beginverse").count(), 2);
+    }
+
+    #[test]
+    fn plain_to_chordpro_to_plain_round_trip_preserves_sections_and_chords() {
+        let content = "Title\n\n\
+                        Verse 1:\n\
+                        C       G\n\
+                        First verse line\n\
+                        \n\
+                        Verse 2:\n\
+                        Second verse line\n";
+
+        let mut song = parse(content);
+        let chordpro = song.to_chordpro(None).expect("should render");
+
+        let mut reparsed =
+            SongParser::parse(&chordpro, InputFormat::ChordPro).expect("should reparse");
+
+        let out = reparsed.to_latex(Notation::English, None).expect("should render");
+        assert!(out.contains("\\[C]First"));
+        assert!(out.contains("\\[G]"));
+        assert!(out.contains("Second verse line"));
+        assert_eq!(out.matches("\\beginverse").count(), 2);
+    }
+
+    #[test]
+    fn transpose_shifts_chord_roots_while_preserving_suffixes() {
+        let content = "Title\nkey: C\n\nVerse 1:\nCmaj7    G\nHello world\n";
+        let mut song = parse(content);
+
+        song.transpose(2, false);
+
+        let out = song.to_latex(Notation::English, None).expect("should render");
+        assert!(out.contains("\\[Dmaj7]"));
+        assert!(out.contains("\\[A]"));
+    }
+
+    #[test]
+    fn german_notation_spells_b_as_h() {
+        let content = "Title\nkey: C\n\nVerse 1:\nB\nLyric\n";
+        let mut song = parse(content);
+
+        let out = song.to_latex(Notation::German, None).expect("should render");
+        assert!(out.contains("\\[H]"));
+    }
+
+    #[test]
+    fn nashville_notation_uses_scale_degrees_relative_to_the_key() {
+        let content = "Title\nkey: C\n\nVerse 1:\nG\nLyric\n";
+        let mut song = parse(content);
+
+        let out = song.to_latex(Notation::Nashville, None).expect("should render");
+        assert!(out.contains("\\[5]"));
+    }
+
+    #[test]
+    fn to_html_does_not_panic_when_a_widened_slash_chord_overflows_the_gap() {
+        // Under Nashville in key D, `C/C` respells to the wider `b7/b7`,
+        // leaving no room before the following `G` at its original offset.
+        let content = "Title\nkey: D\n\nVerse 1:\nC/C G\nLyric\n";
+        let mut song = parse(content);
+
+        let out = song.to_html(Notation::Nashville, None).expect("should render");
+        assert!(out.contains("<b>"));
+    }
+
+    #[test]
+    fn json_round_trip_preserves_song_structure() {
+        let content = "Title\nartist: Some Band\nkey: C\n\nVerse 1:\nC\nHello\n";
+        let song = parse(content);
+
+        let json = serde_json::to_string(&song).expect("should serialize");
+        let mut restored: Song = serde_json::from_str(&json).expect("should deserialize");
+
+        let out = restored.to_latex(Notation::English, None).expect("should render");
+        assert!(out.contains("Hello"));
+        assert!(out.contains("[by={Some Band}"));
+    }
+}